@@ -6,6 +6,7 @@ use ark_crypto_primitives::crh::{
     CRHSchemeGadget,
 };
 use ark_ff::PrimeField;
+use ark_r1cs_std::uint8::UInt8;
 use ark_relations::r1cs::SynthesisError;
 
 // A gadget to hash the header of a block
@@ -15,14 +16,20 @@ pub struct BlockHeaderHashGadget<F: PrimeField> {
 }
 
 impl<F: PrimeField> BlockHeaderHashGadget<F> {
-    // The header of a block is hashed twice using SHA256
-    // See: https://developer.bitcoin.org/reference/block_chain.html#block-headers
-    pub fn hash_block_header(header: BlockHeaderVar<F>) -> Result<DigestVar<F>, SynthesisError> {
+    // Hashes an arbitrary byte string twice with SHA256, the primitive Bitcoin
+    // uses both for block hashes and for combining merkle nodes.
+    pub fn double_sha256(data: &[UInt8<F>]) -> Result<DigestVar<F>, SynthesisError> {
         let unit_var = UnitVar::default();
-        let sha256_1 = Sha256Gadget::evaluate(&unit_var, &header.block_header)?;
+        let sha256_1 = Sha256Gadget::evaluate(&unit_var, data)?;
         let sha256_2 = Sha256Gadget::evaluate(&unit_var, &sha256_1.0)?;
         Ok(sha256_2)
     }
+
+    // The header of a block is hashed twice using SHA256
+    // See: https://developer.bitcoin.org/reference/block_chain.html#block-headers
+    pub fn hash_block_header(header: BlockHeaderVar<F>) -> Result<DigestVar<F>, SynthesisError> {
+        Self::double_sha256(&header.block_header)
+    }
 }
 
 #[cfg(test)]
@@ -1,14 +1,17 @@
+use std::cmp::Ordering;
 use std::marker::PhantomData;
 
 use super::BlockHeaderVar;
 use ark_ff::PrimeField;
 use ark_r1cs_std::{
     alloc::AllocVar,
+    boolean::Boolean,
+    eq::EqGadget,
     fields::{fp::FpVar, FieldVar},
-    uint8::UInt8,
-    ToBitsGadget, ToConstraintFieldGadget,
+    R1CSVar, ToBitsGadget, ToConstraintFieldGadget,
 };
 use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use num_bigint::BigUint;
 
 // A gadget to calculate the target pow value from the bits field of the block header
 #[derive(Clone, Debug)]
@@ -17,26 +20,86 @@ pub struct BlockTargetGadget<F: PrimeField> {
 }
 
 impl<F: PrimeField> BlockTargetGadget<F> {
+    // The mainnet proof-of-work limit (regtest/testnet differ): mantissa 0xffff with
+    // exponent 0x1d, i.e. 0xffff * 256^26.
+    pub fn mainnet_pow_limit() -> F {
+        F::from(BigUint::from(0xffffu32) << (8 * 26))
+    }
+
     // The target is calculated from the bits field of the block header
     // Target is computed from the "bits" field. The bits field is found in the 72..76 bytes of the block header
+    // This implements the full compact encoding: exponents below 3 right-shift the mantissa rather
+    // than scaling it up, the mantissa's 0x00800000 sign bit is rejected, and the decoded target may
+    // not exceed `pow_limit`.
     pub fn calculate_target(
         cs: ConstraintSystemRef<F>,
         header: BlockHeaderVar<F>,
+        pow_limit: F,
     ) -> Result<FpVar<F>, SynthesisError> {
-        let mut bits = UInt8::new_witness_vec(cs.clone(), &vec![0u8; 4])?;
-        bits.clone_from_slice(&header.block_header[72..76]);
+        let bits = header.bits();
 
-        // Compute base^{exponent}
-        let exponent = &[bits[3].clone()].to_constraint_field()?[0];
+        // The mantissa is the little-endian value of the low three bytes; its high
+        // bit (0x00800000, i.e. bit 7 of the most significant byte) flags a negative
+        // target and must be zero for a canonical, non-negative encoding.
+        let mantissa = bits[0..3].to_constraint_field()?[0].clone();
+        let sign_bit = bits[2].to_bits_le()?[7].clone();
+        sign_bit.enforce_equal(&Boolean::constant(false))?;
+
+        // The exponent is a witnessed byte; select between scaling the mantissa up by
+        // 256^(exponent - 3) and shifting it down by 256^(3 - exponent).
+        let exponent = [bits[3].clone()].to_constraint_field()?[0].clone();
         let three = FpVar::<F>::new_constant(cs.clone(), F::from(3 as u8))?;
-        let exponent = exponent - three;
-        let base_exponent = Base256Gadget::calculate_base256_exponent(cs.clone(), exponent)?;
+        let zero = FpVar::<F>::zero();
+        let is_small = exponent.is_cmp(&three, Ordering::Less, false)?;
+
+        // Bound the exponent so its worst-case decoded value (mantissa < 2^24,
+        // scaled by 256^(exponent - 3)) stays well under (p - 1) / 2, the domain
+        // `is_cmp`/`enforce_cmp` require of their operands. Forcing the exponent
+        // into a single byte is not enough: `256^(exponent - 3)` for an exponent
+        // near 255 overflows the scalar modulus and wraps, so a non-canonical
+        // exponent could land the reduced target below `pow_limit` even though
+        // the real target is enormous. This bound is generous relative to any
+        // real network's canonical maximum (mainnet's is 0x1d) but still keeps
+        // the exponentiation itself from ever wrapping.
+        let max_exponent = FpVar::<F>::new_constant(
+            cs.clone(),
+            F::from((F::MODULUS_BIT_SIZE as u64 - 32) / 8 + 3),
+        )?;
+        exponent.enforce_cmp(&max_exponent, Ordering::Less, true)?;
+
+        // Feed each exponentiation only the difference it actually needs: a zero
+        // exponent on the branch that is not selected. This keeps `3 - exponent`
+        // from underflowing to a ~254-bit value on the common (exponent >= 3) path,
+        // and keeps `low_divisor` a small byte-bounded value so the range comparison
+        // below stays within the signed-comparison domain.
+        let high_exp = is_small.select(&zero, &(&exponent - &three))?;
+        let low_exp = is_small.select(&(&three - &exponent), &zero)?;
+
+        // Upper branch: mantissa * 256^(exponent - 3).
+        let high_factor = Base256Gadget::calculate_base256_exponent(cs.clone(), high_exp)?;
+        let high_target = &mantissa * &high_factor;
+
+        // Lower branch: mantissa / 256^(3 - exponent), expressed with a witnessed
+        // quotient / remainder as integer division.
+        let low_divisor = Base256Gadget::calculate_base256_exponent(cs.clone(), low_exp)?;
+        let quotient = FpVar::new_witness(cs.clone(), || {
+            let mantissa: BigUint = mantissa.value()?.into();
+            let divisor: BigUint = low_divisor.value()?.into();
+            Ok(F::from(mantissa / divisor))
+        })?;
+        let remainder = FpVar::new_witness(cs.clone(), || {
+            let mantissa: BigUint = mantissa.value()?.into();
+            let divisor: BigUint = low_divisor.value()?.into();
+            Ok(F::from(mantissa % divisor))
+        })?;
+        (&quotient * &low_divisor + &remainder).enforce_equal(&mantissa)?;
+        remainder.enforce_cmp(&low_divisor, Ordering::Less, false)?;
 
-        // Compute the mantissa
-        let mantissa = &bits[0..3].to_constraint_field()?[0];
+        let target = is_small.select(&quotient, &high_target)?;
 
-        // Compute target
-        let target = mantissa * base_exponent;
+        // Reject targets above the proof-of-work limit.
+        let pow_limit = FpVar::new_constant(cs.clone(), pow_limit)?;
+        target.enforce_cmp(&pow_limit, Ordering::Less, true)?;
 
         Ok(target)
     }
@@ -49,6 +112,9 @@ pub struct Base256Gadget<F: PrimeField> {
 }
 
 impl<F: PrimeField> Base256Gadget<F> {
+    // The compact exponent is a single byte, so only the low 8 bits may be set;
+    // enforcing the higher bits to zero both bounds the exponent and avoids
+    // squaring over the full field width.
     pub fn calculate_base256_exponent(
         cs: ConstraintSystemRef<F>,
         exponent: FpVar<F>,
@@ -56,7 +122,10 @@ impl<F: PrimeField> Base256Gadget<F> {
         let mut result = FpVar::<F>::new_witness(cs.clone(), || Ok(F::from(1 as u16)))?;
         let mut base = FpVar::<F>::new_constant(cs.clone(), F::from(256 as u16))?;
         let exponent_bits = exponent.to_bits_le()?;
-        for bit in exponent_bits {
+        for bit in exponent_bits.iter().skip(8) {
+            bit.enforce_equal(&Boolean::constant(false))?;
+        }
+        for bit in exponent_bits.iter().take(8) {
             let result_if_true = result.clone() * base.clone();
             let result_if_false = result.clone();
             result = bit.select(&result_if_true, &result_if_false)?;
@@ -118,12 +187,99 @@ mod tests {
         )
         .unwrap();
 
-        let computed_target =
-            super::BlockTargetGadget::calculate_target(cs.clone(), block_header_var)
-                .unwrap()
-                .value()
-                .unwrap();
+        let computed_target = super::BlockTargetGadget::calculate_target(
+            cs.clone(),
+            block_header_var,
+            super::BlockTargetGadget::mainnet_pow_limit(),
+        )
+        .unwrap()
+        .value()
+        .unwrap();
 
         assert_eq!(computed_target, expected_target.into());
     }
+
+    // Builds a header whose only meaningful field for the target maths is the
+    // compact-encoded `bits` at bytes 72..76 (mantissa little-endian, then exponent).
+    fn header_with_bits(mantissa: [u8; 3], exponent: u8) -> BlockHeader {
+        let mut bytes = get_test_block().blockHeaders[0].clone();
+        bytes[72..75].copy_from_slice(&mantissa);
+        bytes[75] = exponent;
+        BlockHeader { block_header: bytes }
+    }
+
+    #[test]
+    fn calculate_target_right_shifts_small_exponent() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // exponent = 2 takes the right-shift branch: target = mantissa >> 8.
+        let header = header_with_bits([0x56, 0x34, 0x12], 2);
+        let block_header_var = BlockHeaderVar::<Fr>::new_variable(
+            cs.clone(),
+            || Ok(&header),
+            AllocationMode::Witness,
+        )
+        .unwrap();
+
+        let computed_target = super::BlockTargetGadget::calculate_target(
+            cs.clone(),
+            block_header_var,
+            super::BlockTargetGadget::mainnet_pow_limit(),
+        )
+        .unwrap()
+        .value()
+        .unwrap();
+
+        let expected_target = BigUint::from_bytes_le(&[0x56, 0x34, 0x12]) >> 8;
+        assert_eq!(computed_target, Fr::from(expected_target));
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn calculate_target_rejects_sign_bit() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // exponent = 3 is a no-op scale, but the mantissa's high bit (0x00800000)
+        // flags a negative target and must be rejected.
+        let header = header_with_bits([0x00, 0x00, 0x80], 3);
+        let block_header_var = BlockHeaderVar::<Fr>::new_variable(
+            cs.clone(),
+            || Ok(&header),
+            AllocationMode::Witness,
+        )
+        .unwrap();
+
+        super::BlockTargetGadget::calculate_target(
+            cs.clone(),
+            block_header_var,
+            super::BlockTargetGadget::mainnet_pow_limit(),
+        )
+        .unwrap();
+
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn calculate_target_rejects_above_pow_limit() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // mantissa 0xffff at exponent 0x1e (30) decodes to 256x the mainnet
+        // pow limit (mantissa 0xffff at exponent 0x1d).
+        let header = header_with_bits([0xff, 0xff, 0x00], 0x1e);
+        let block_header_var = BlockHeaderVar::<Fr>::new_variable(
+            cs.clone(),
+            || Ok(&header),
+            AllocationMode::Witness,
+        )
+        .unwrap();
+
+        super::BlockTargetGadget::calculate_target(
+            cs.clone(),
+            block_header_var,
+            super::BlockTargetGadget::mainnet_pow_limit(),
+        )
+        .unwrap();
+
+        assert!(!cs.is_satisfied().unwrap());
+    }
 }
@@ -0,0 +1,295 @@
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+use super::{
+    calculate_target_gadget::{Base256Gadget, BlockTargetGadget},
+    BlockHeaderVar,
+};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{
+    alloc::AllocVar,
+    eq::EqGadget,
+    fields::{fp::FpVar, FieldVar},
+    R1CSVar, ToConstraintFieldGadget,
+};
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use num_bigint::BigUint;
+
+// The number of blocks between two difficulty retargets.
+pub const RETARGET_INTERVAL: u32 = 2016;
+// The targeted timespan of a retarget period, in seconds (2016 blocks * 600 seconds).
+pub const TARGET_TIMESPAN: u64 = 1_209_600;
+
+// The difficulty context a caller threads into `BTCBlockCheckerGadget::check_block`.
+// At a retarget boundary it carries the previous period's bracketing headers and the
+// old target; otherwise it carries the parent header so the `bits` can be checked
+// unchanged.
+#[derive(Clone, Debug)]
+pub enum DifficultyCheck<F: PrimeField> {
+    Retarget {
+        first_header: BlockHeaderVar<F>,
+        last_header: BlockHeaderVar<F>,
+        old_target: FpVar<F>,
+    },
+    Inherit {
+        parent_header: BlockHeaderVar<F>,
+    },
+}
+
+// A gadget to enforce Bitcoin's difficulty retargeting rule.
+// Every `RETARGET_INTERVAL` blocks the network recomputes the target from the
+// time it took to mine the previous period, clamped to a factor of four in
+// either direction. For all other heights the `bits` field must stay untouched.
+// See: https://developer.bitcoin.org/reference/block_chain.html#target-nbits
+#[derive(Clone, Debug)]
+pub struct DifficultyAdjustmentGadget<F: PrimeField> {
+    _f: PhantomData<F>,
+}
+
+impl<F: PrimeField> DifficultyAdjustmentGadget<F> {
+    // Computes the retargeted value from the first and last headers of the
+    // previous period and the old target. The result is the clamped, saturated
+    // `old_target * actual_timespan / target_timespan`.
+    pub fn calculate_new_target(
+        cs: ConstraintSystemRef<F>,
+        first_header: BlockHeaderVar<F>,
+        last_header: BlockHeaderVar<F>,
+        old_target: FpVar<F>,
+        pow_limit: F,
+    ) -> Result<FpVar<F>, SynthesisError> {
+        let first_time = first_header.time()?;
+        let last_time = last_header.time()?;
+
+        // The timestamps themselves are 32-bit values, well within the range
+        // `is_cmp` requires, so detect a negative timespan there before subtracting:
+        // `last_time - first_time` would otherwise wrap to a ~p field element when
+        // `last_time < first_time`, which both points the clamp the wrong way and
+        // falls outside the range `is_cmp` assumes of its operands.
+        let is_negative = last_time.is_cmp(&first_time, Ordering::Less, false)?;
+        let actual_timespan = is_negative.select(&FpVar::zero(), &(&last_time - &first_time))?;
+
+        // Clamp the measured timespan to [target_timespan / 4, target_timespan * 4].
+        // A negative timespan clamps the same direction as a too-short one: down to
+        // the minimum.
+        let min_timespan = FpVar::new_constant(cs.clone(), F::from(TARGET_TIMESPAN / 4))?;
+        let max_timespan = FpVar::new_constant(cs.clone(), F::from(TARGET_TIMESPAN * 4))?;
+        let below =
+            is_negative.or(&actual_timespan.is_cmp(&min_timespan, Ordering::Less, false)?)?;
+        let clamped = below.select(&min_timespan, &actual_timespan)?;
+        let above = clamped.is_cmp(&max_timespan, Ordering::Greater, false)?;
+        let clamped = above.select(&max_timespan, &clamped)?;
+
+        // new_target = old_target * clamped / target_timespan, using a witnessed
+        // quotient / remainder to express the integer division in the field.
+        let numerator = old_target * clamped;
+        let target_timespan = FpVar::new_constant(cs.clone(), F::from(TARGET_TIMESPAN))?;
+        let quotient = FpVar::new_witness(cs.clone(), || {
+            let num: BigUint = numerator.value()?.into();
+            Ok(F::from(num / BigUint::from(TARGET_TIMESPAN)))
+        })?;
+        let remainder = FpVar::new_witness(cs.clone(), || {
+            let num: BigUint = numerator.value()?.into();
+            Ok(F::from(num % BigUint::from(TARGET_TIMESPAN)))
+        })?;
+        (&quotient * &target_timespan + &remainder).enforce_equal(&numerator)?;
+        remainder.enforce_cmp(&target_timespan, Ordering::Less, false)?;
+
+        // Saturate at the proof-of-work limit.
+        let pow_limit = FpVar::new_constant(cs.clone(), pow_limit)?;
+        let overflows = quotient.is_cmp(&pow_limit, Ordering::Greater, false)?;
+        overflows.select(&pow_limit, &quotient)
+    }
+
+    // Enforces the retargeting rule at a period boundary. Bitcoin re-encodes the
+    // recomputed target to compact form (`GetCompact`), truncating the mantissa to
+    // three significant bytes, so the on-chain `bits` decode to `new_target` rounded
+    // *down* to a whole multiple of one mantissa unit, `256^(exponent - 3)`. An exact
+    // equality check would wrongly reject legitimate retarget blocks, so we enforce
+    // the bound the round-trip guarantees instead:
+    //   decoded_target <= new_target < decoded_target + unit.
+    pub fn enforce_retarget(
+        cs: ConstraintSystemRef<F>,
+        first_header: BlockHeaderVar<F>,
+        last_header: BlockHeaderVar<F>,
+        new_header: BlockHeaderVar<F>,
+        old_target: FpVar<F>,
+        pow_limit: F,
+    ) -> Result<(), SynthesisError> {
+        let new_target = Self::calculate_new_target(
+            cs.clone(),
+            first_header,
+            last_header,
+            old_target,
+            pow_limit,
+        )?;
+        let decoded_target =
+            BlockTargetGadget::calculate_target(cs.clone(), new_header.clone(), pow_limit)?;
+
+        // One mantissa unit for the new block's exponent (>= 3 in the retarget range).
+        let exponent = [new_header.bits()[3].clone()].to_constraint_field()?[0].clone();
+        let three = FpVar::new_constant(cs.clone(), F::from(3 as u8))?;
+        let unit = Base256Gadget::calculate_base256_exponent(cs, &exponent - &three)?;
+
+        decoded_target.enforce_cmp(&new_target, Ordering::Less, true)?;
+        new_target.enforce_cmp(&(&decoded_target + &unit), Ordering::Less, false)
+    }
+
+    // Dispatches on the supplied difficulty context, enforcing either the retarget
+    // rule or the unchanged-`bits` rule against `new_header`.
+    pub fn enforce(
+        cs: ConstraintSystemRef<F>,
+        new_header: BlockHeaderVar<F>,
+        check: DifficultyCheck<F>,
+        pow_limit: F,
+    ) -> Result<(), SynthesisError> {
+        match check {
+            DifficultyCheck::Retarget {
+                first_header,
+                last_header,
+                old_target,
+            } => Self::enforce_retarget(
+                cs,
+                first_header,
+                last_header,
+                new_header,
+                old_target,
+                pow_limit,
+            ),
+            DifficultyCheck::Inherit { parent_header } => {
+                Self::enforce_bits_unchanged(parent_header, new_header)
+            }
+        }
+    }
+
+    // Outside of a period boundary the `bits` field must be identical to the
+    // parent's. The field lives at bytes 72..76 of the header.
+    pub fn enforce_bits_unchanged(
+        parent_header: BlockHeaderVar<F>,
+        new_header: BlockHeaderVar<F>,
+    ) -> Result<(), SynthesisError> {
+        new_header.bits().enforce_equal(parent_header.bits())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::{BlockHeader, BlockHeaderVar};
+    use crate::tests::get_test_block;
+    use crate::get_target;
+    use ark_r1cs_std::{
+        alloc::{AllocVar, AllocationMode},
+        R1CSVar,
+    };
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_vesta::Fr;
+
+    // Builds a header whose only meaningful field for the retarget maths is the
+    // little-endian timestamp at bytes 68..72.
+    fn header_with_time(time: u32) -> BlockHeader {
+        let mut bytes = get_test_block().blockHeaders[0].clone();
+        bytes[68..72].copy_from_slice(&time.to_le_bytes());
+        BlockHeader { block_header: bytes }
+    }
+
+    #[test]
+    fn calculate_new_target_matches_reference() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // A timespan exactly equal to the target leaves the target unchanged.
+        let first = header_with_time(1_000_000);
+        let last = header_with_time(1_000_000 + TARGET_TIMESPAN as u32);
+        let old_target = get_target(&get_test_block().blockHeaders[0]);
+
+        let first_var =
+            BlockHeaderVar::<Fr>::new_variable(cs.clone(), || Ok(&first), AllocationMode::Witness)
+                .unwrap();
+        let last_var =
+            BlockHeaderVar::<Fr>::new_variable(cs.clone(), || Ok(&last), AllocationMode::Witness)
+                .unwrap();
+        let old_target_var =
+            FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(old_target.clone()))).unwrap();
+
+        let pow_limit = BlockTargetGadget::<Fr>::mainnet_pow_limit();
+        let computed = DifficultyAdjustmentGadget::calculate_new_target(
+            cs.clone(),
+            first_var,
+            last_var,
+            old_target_var,
+            pow_limit,
+        )
+        .unwrap();
+
+        assert_eq!(computed.value().unwrap(), Fr::from(old_target));
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn calculate_new_target_retargets_on_changed_timespan() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // A timespan twice the target halves the network's measured difficulty,
+        // i.e. doubles the target, without hitting either clamp bound.
+        let first = header_with_time(1_000_000);
+        let last = header_with_time(1_000_000 + 2 * TARGET_TIMESPAN as u32);
+        let old_target = get_target(&get_test_block().blockHeaders[0]);
+
+        let first_var =
+            BlockHeaderVar::<Fr>::new_variable(cs.clone(), || Ok(&first), AllocationMode::Witness)
+                .unwrap();
+        let last_var =
+            BlockHeaderVar::<Fr>::new_variable(cs.clone(), || Ok(&last), AllocationMode::Witness)
+                .unwrap();
+        let old_target_var =
+            FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(old_target.clone()))).unwrap();
+
+        let pow_limit = BlockTargetGadget::<Fr>::mainnet_pow_limit();
+        let computed = DifficultyAdjustmentGadget::calculate_new_target(
+            cs.clone(),
+            first_var,
+            last_var,
+            old_target_var,
+            pow_limit,
+        )
+        .unwrap();
+
+        assert_eq!(
+            computed.value().unwrap(),
+            Fr::from(old_target * BigUint::from(2u8))
+        );
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn enforce_retarget_rejects_bits_disagreeing_with_computed_target() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // A timespan twice the target computes a genuinely different new target,
+        // but the candidate header's `bits` still encode the unchanged old one.
+        let first = header_with_time(1_000_000);
+        let last = header_with_time(1_000_000 + 2 * TARGET_TIMESPAN as u32);
+        let old_target = get_target(&get_test_block().blockHeaders[0]);
+
+        let first_var =
+            BlockHeaderVar::<Fr>::new_variable(cs.clone(), || Ok(&first), AllocationMode::Witness)
+                .unwrap();
+        let last_var =
+            BlockHeaderVar::<Fr>::new_variable(cs.clone(), || Ok(&last), AllocationMode::Witness)
+                .unwrap();
+        let old_target_var =
+            FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(old_target))).unwrap();
+
+        let pow_limit = BlockTargetGadget::<Fr>::mainnet_pow_limit();
+        DifficultyAdjustmentGadget::enforce_retarget(
+            cs.clone(),
+            first_var,
+            last_var.clone(),
+            last_var,
+            old_target_var,
+            pow_limit,
+        )
+        .unwrap();
+
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
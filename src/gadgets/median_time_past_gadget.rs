@@ -0,0 +1,131 @@
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+use super::BlockHeaderVar;
+use ark_ff::PrimeField;
+use ark_r1cs_std::{alloc::AllocVar, fields::fp::FpVar};
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+
+// The number of preceding blocks a timestamp is compared against.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+// A block's timestamp may not be more than two hours ahead of network time.
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+// A gadget enforcing Bitcoin's median-time-past rule: a block's timestamp must be
+// strictly greater than the median of the previous eleven blocks' timestamps.
+// The median is computed by a fixed comparator network of conditional swaps so
+// that the constraint count is independent of the witnessed timestamps.
+#[derive(Clone, Debug)]
+pub struct MedianTimePastGadget<F: PrimeField> {
+    _f: PhantomData<F>,
+}
+
+impl<F: PrimeField> MedianTimePastGadget<F> {
+    // A single comparator: returns the pair sorted ascending.
+    fn compare_swap(
+        a: &FpVar<F>,
+        b: &FpVar<F>,
+    ) -> Result<(FpVar<F>, FpVar<F>), SynthesisError> {
+        let a_gt_b = a.is_cmp(b, Ordering::Greater, false)?;
+        let min = a_gt_b.select(b, a)?;
+        let max = a_gt_b.select(a, b)?;
+        Ok((min, max))
+    }
+
+    // Sorts the timestamps with a fixed network and returns the middle element.
+    pub fn median(times: &[FpVar<F>]) -> Result<FpVar<F>, SynthesisError> {
+        let mut sorted = times.to_vec();
+        let n = sorted.len();
+        for i in 0..n {
+            for j in 0..n - 1 - i {
+                let a = sorted[j].clone();
+                let b = sorted[j + 1].clone();
+                let (min, max) = Self::compare_swap(&a, &b)?;
+                sorted[j] = min;
+                sorted[j + 1] = max;
+            }
+        }
+        Ok(sorted[n / 2].clone())
+    }
+
+    // Enforces that `current_time` is strictly greater than the median of the
+    // `MEDIAN_TIME_SPAN` preceding headers' timestamps and no more than
+    // `MAX_FUTURE_BLOCK_TIME` ahead of the caller-supplied `network_time` (the
+    // node's adjusted clock, which the circuit has no other way to learn).
+    pub fn enforce(
+        cs: ConstraintSystemRef<F>,
+        current_time: &FpVar<F>,
+        window: &[BlockHeaderVar<F>],
+        network_time: &FpVar<F>,
+    ) -> Result<(), SynthesisError> {
+        assert_eq!(
+            window.len(),
+            MEDIAN_TIME_SPAN,
+            "median-time-past requires exactly {MEDIAN_TIME_SPAN} preceding headers"
+        );
+        let times = window
+            .iter()
+            .map(|header| header.time())
+            .collect::<Result<Vec<_>, _>>()?;
+        let median = Self::median(&times)?;
+        current_time.enforce_cmp(&median, Ordering::Greater, false)?;
+
+        let max_future = FpVar::new_constant(cs, F::from(MAX_FUTURE_BLOCK_TIME))?;
+        current_time.enforce_cmp(&(network_time + max_future), Ordering::Less, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::{BlockHeader, BlockHeaderVar};
+    use crate::tests::get_test_block;
+    use ark_r1cs_std::{
+        alloc::{AllocVar, AllocationMode},
+        R1CSVar,
+    };
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_vesta::Fr;
+
+    fn header_with_time(time: u32) -> BlockHeader {
+        let mut bytes = get_test_block().blockHeaders[0].clone();
+        bytes[68..72].copy_from_slice(&time.to_le_bytes());
+        BlockHeader { block_header: bytes }
+    }
+
+    #[test]
+    fn median_of_shuffled_window() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let raw = [30u32, 10, 80, 50, 20, 70, 0, 90, 40, 100, 60];
+        let times = raw
+            .iter()
+            .map(|t| FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(*t))).unwrap())
+            .collect::<Vec<_>>();
+        let median = MedianTimePastGadget::median(&times).unwrap();
+        // Sorted the window is 0,10,20,...,100; the sixth element is 50.
+        assert_eq!(median.value().unwrap(), Fr::from(50u32));
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn enforce_accepts_timestamp_above_median() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let window = (0..MEDIAN_TIME_SPAN as u32)
+            .map(|i| {
+                let header = header_with_time(1_000 + i);
+                BlockHeaderVar::<Fr>::new_variable(
+                    cs.clone(),
+                    || Ok(&header),
+                    AllocationMode::Witness,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        // The median of 1000..1010 is 1005, so 1006 must be accepted.
+        let current_time = FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(1_006u32))).unwrap();
+        // Network time equals the block's time, so the two-hour future bound is satisfied.
+        let network_time = FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(1_006u32))).unwrap();
+        MedianTimePastGadget::enforce(cs.clone(), &current_time, &window, &network_time).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
@@ -0,0 +1,193 @@
+use std::marker::PhantomData;
+
+use super::{block_header_hash_gadget::BlockHeaderHashGadget, BlockHeaderVar};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{boolean::Boolean, eq::EqGadget, uint8::UInt8};
+use ark_relations::r1cs::SynthesisError;
+
+// A gadget proving that a transaction is committed to by a block's merkle root.
+// The merkle root lives at bytes `36..68` of the header. Leaves and internal
+// nodes are double-SHA256 hashes combined in Bitcoin's little-endian order, and
+// whenever a level has an odd number of nodes the last hash is paired with
+// itself (the per-level `duplicate_self` flag).
+//
+// All hashes are handled in Bitcoin's internal (little-endian) byte order, which
+// is the order the header stores the merkle root in. Displayed txids are the
+// byte-reversal of that order, so a caller starting from a displayed txid must
+// reverse its 32 bytes before feeding it in.
+#[derive(Clone, Debug)]
+pub struct MerkleInclusionGadget<F: PrimeField> {
+    _f: PhantomData<F>,
+}
+
+impl<F: PrimeField> MerkleInclusionGadget<F> {
+    // Double-SHA256 of the concatenation of two 32-byte children.
+    fn hash_nodes(
+        left: &[UInt8<F>],
+        right: &[UInt8<F>],
+    ) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        let mut data = left.to_vec();
+        data.extend_from_slice(right);
+        Ok(BlockHeaderHashGadget::double_sha256(&data)?.0)
+    }
+
+    // Recomputes the merkle root from a leaf and its authentication path.
+    // `path_is_left[i]` is true when the sibling at level `i` sits to the left of
+    // the running hash; `duplicate_self[i]` is true when the level had an odd
+    // count and the running hash must be paired with itself instead.
+    pub fn compute_root(
+        leaf: &[UInt8<F>],
+        siblings: &[Vec<UInt8<F>>],
+        path_is_left: &[Boolean<F>],
+        duplicate_self: &[Boolean<F>],
+    ) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        let mut current = leaf.to_vec();
+        for ((sibling, is_left), dup) in siblings.iter().zip(path_is_left).zip(duplicate_self) {
+            // When the level is odd the sibling is a copy of the running hash.
+            let sibling = sibling
+                .iter()
+                .zip(current.iter())
+                .map(|(s, c)| dup.select(c, s))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // Order the two children according to the path bit.
+            let left = sibling
+                .iter()
+                .zip(current.iter())
+                .map(|(s, c)| is_left.select(s, c))
+                .collect::<Result<Vec<_>, _>>()?;
+            let right = sibling
+                .iter()
+                .zip(current.iter())
+                .map(|(s, c)| is_left.select(c, s))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            current = Self::hash_nodes(&left, &right)?;
+        }
+        Ok(current)
+    }
+
+    // Enforces that `leaf` is included under the header's merkle root.
+    pub fn enforce_inclusion(
+        header: &BlockHeaderVar<F>,
+        leaf: &[UInt8<F>],
+        siblings: &[Vec<UInt8<F>>],
+        path_is_left: &[Boolean<F>],
+        duplicate_self: &[Boolean<F>],
+    ) -> Result<(), SynthesisError> {
+        let root = Self::compute_root(leaf, siblings, path_is_left, duplicate_self)?;
+        root.as_slice().enforce_equal(header.merkle_root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::{BlockHeader, BlockHeaderVar};
+    use crate::tests::get_test_block;
+    use ark_r1cs_std::alloc::{AllocVar, AllocationMode};
+    use ark_relations::r1cs::{ConstraintSystem, ConstraintSystemRef};
+    use ark_vesta::Fr;
+    use sha2::{Digest, Sha256};
+
+    fn double_sha256(data: &[u8]) -> Vec<u8> {
+        let first = Sha256::digest(data);
+        Sha256::digest(first).to_vec()
+    }
+
+    // Allocates a header whose merkle root is `root`.
+    fn header_with_root(cs: &ConstraintSystemRef<Fr>, root: &[u8]) -> BlockHeaderVar<Fr> {
+        let mut header_bytes = get_test_block().blockHeaders[0].clone();
+        header_bytes[36..68].copy_from_slice(root);
+        let block_header = BlockHeader { block_header: header_bytes };
+        BlockHeaderVar::<Fr>::new_variable(
+            cs.clone(),
+            || Ok(&block_header),
+            AllocationMode::Witness,
+        )
+        .unwrap()
+    }
+
+    fn bits(cs: &ConstraintSystemRef<Fr>, values: &[bool]) -> Vec<Boolean<Fr>> {
+        values
+            .iter()
+            .map(|v| Boolean::new_witness(cs.clone(), || Ok(*v)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn right_sibling_inclusion() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // A two-transaction block: root = dsha256(txid_a || txid_b), proving txid_a.
+        let txid_a = [3u8; 32];
+        let txid_b = [7u8; 32];
+        let root = double_sha256(&[txid_a.as_slice(), txid_b.as_slice()].concat());
+        let header = header_with_root(&cs, &root);
+
+        let leaf = UInt8::new_witness_vec(cs.clone(), &txid_a).unwrap();
+        let sibling = UInt8::new_witness_vec(cs.clone(), &txid_b).unwrap();
+        // The sibling sits to the right; the level is even.
+        let is_left = bits(&cs, &[false]);
+        let dup = bits(&cs, &[false]);
+
+        MerkleInclusionGadget::enforce_inclusion(&header, &leaf, &[sibling], &is_left, &dup)
+            .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn left_sibling_inclusion() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // Same block, but now proving txid_b, whose sibling is to its left.
+        let txid_a = [3u8; 32];
+        let txid_b = [7u8; 32];
+        let root = double_sha256(&[txid_a.as_slice(), txid_b.as_slice()].concat());
+        let header = header_with_root(&cs, &root);
+
+        let leaf = UInt8::new_witness_vec(cs.clone(), &txid_b).unwrap();
+        let sibling = UInt8::new_witness_vec(cs.clone(), &txid_a).unwrap();
+        let is_left = bits(&cs, &[true]);
+        let dup = bits(&cs, &[false]);
+
+        MerkleInclusionGadget::enforce_inclusion(&header, &leaf, &[sibling], &is_left, &dup)
+            .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn odd_level_duplicate_inclusion() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        // A three-transaction block. The odd node `c` is paired with itself.
+        //   level 0: [a, b, c]  -> ab = H(a||b), cc = H(c||c)
+        //   level 1: [ab, cc]   -> root = H(ab||cc)
+        // We prove inclusion of `c`.
+        let txid_a = [3u8; 32];
+        let txid_b = [7u8; 32];
+        let txid_c = [9u8; 32];
+        let ab = double_sha256(&[txid_a.as_slice(), txid_b.as_slice()].concat());
+        let cc = double_sha256(&[txid_c.as_slice(), txid_c.as_slice()].concat());
+        let root = double_sha256(&[ab.as_slice(), cc.as_slice()].concat());
+        let header = header_with_root(&cs, &root);
+
+        let leaf = UInt8::new_witness_vec(cs.clone(), &txid_c).unwrap();
+        // Level 0: `c` is duplicated (its sibling is itself, on the right).
+        // Level 1: `cc` sits to the right of `ab`, so the sibling is on the left.
+        let sibling_level0 = UInt8::new_witness_vec(cs.clone(), &txid_c).unwrap();
+        let sibling_level1 = UInt8::new_witness_vec(cs.clone(), &ab).unwrap();
+        let is_left = bits(&cs, &[false, true]);
+        let dup = bits(&cs, &[true, false]);
+
+        MerkleInclusionGadget::enforce_inclusion(
+            &header,
+            &leaf,
+            &[sibling_level0, sibling_level1],
+            &is_left,
+            &dup,
+        )
+        .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
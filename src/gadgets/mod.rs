@@ -1,25 +1,51 @@
 use crate::utils::{BlockHeaderVar, BlockVar};
 use ark_ff::PrimeField;
-use ark_r1cs_std::{eq::EqGadget, ToBytesGadget, ToConstraintFieldGadget};
+use ark_r1cs_std::{
+    alloc::AllocVar,
+    boolean::Boolean,
+    eq::EqGadget,
+    fields::{fp::FpVar, FieldVar},
+    R1CSVar, ToBitsGadget, ToBytesGadget, ToConstraintFieldGadget,
+};
 use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use num_bigint::BigUint;
 use std::cmp::Ordering;
 
 use self::{
     block_header_hash_gadget::BlockHeaderHashGadget, calculate_target_gadget::BlockTargetGadget,
+    difficulty_adjustment_gadget::{DifficultyAdjustmentGadget, DifficultyCheck},
+    median_time_past_gadget::MedianTimePastGadget,
 };
 
 pub mod block_header_hash_gadget;
 pub mod calculate_target_gadget;
+pub mod difficulty_adjustment_gadget;
+pub mod median_time_past_gadget;
+pub mod merkle_inclusion_gadget;
 
 pub struct BTCBlockCheckerGadget<F: PrimeField> {
     _f: std::marker::PhantomData<F>,
 }
 
 impl<F: PrimeField> BTCBlockCheckerGadget<F> {
+    // Checks a single block and threads the running proof-of-work accumulator.
+    // `total_work` is the work summed over all previously checked blocks; the
+    // returned value adds this block's contribution and is meant to be exposed as
+    // a public output so recursion can compare the cumulative work of competing
+    // chains.
+    // When `mtp_window` is supplied (the `MEDIAN_TIME_SPAN` preceding headers paired
+    // with the node's network time), the block's timestamp is additionally required
+    // to satisfy Bitcoin's median-time-past and future-time rules.
+    // When `difficulty` is supplied, the block's `bits` are enforced against the
+    // consensus difficulty rule (a retarget at a period boundary, or unchanged from
+    // the parent otherwise) instead of being trusted.
     pub fn check_block(
         cs: ConstraintSystemRef<F>,
         block: BlockVar<F>,
-    ) -> Result<(), SynthesisError> {
+        total_work: FpVar<F>,
+        mtp_window: Option<(Vec<BlockHeaderVar<F>>, FpVar<F>)>,
+        difficulty: Option<DifficultyCheck<F>>,
+    ) -> Result<FpVar<F>, SynthesisError> {
         // Check that block hash is equal to current block hash
         let computed_block_hash =
             BlockHeaderHashGadget::hash_block_header(block.block_header.clone())?;
@@ -30,10 +56,14 @@ impl<F: PrimeField> BTCBlockCheckerGadget<F> {
             .prev_block_hash
             .hash
             .to_bytes()?
-            .enforce_equal(&block.block_header.block_header[4..36])?;
+            .enforce_equal(block.block_header.prev_blockhash())?;
 
         // Compute target
-        let target = BlockTargetGadget::calculate_target(cs.clone(), block.block_header.clone())?;
+        let target = BlockTargetGadget::calculate_target(
+            cs.clone(),
+            block.block_header.clone(),
+            BlockTargetGadget::mainnet_pow_limit(),
+        )?;
 
         // Check pow
         block.block_hash.hash.to_bytes()?.to_constraint_field()?[0].enforce_cmp(
@@ -41,6 +71,139 @@ impl<F: PrimeField> BTCBlockCheckerGadget<F> {
             Ordering::Less,
             false,
         )?;
+
+        // Optionally enforce the median-time-past and future-time rules.
+        if let Some((window, network_time)) = mtp_window {
+            let current_time = block.block_header.time()?;
+            MedianTimePastGadget::enforce(cs.clone(), &current_time, &window, &network_time)?;
+        }
+
+        // Optionally enforce the consensus difficulty rule on this block's `bits`.
+        if let Some(difficulty) = difficulty {
+            DifficultyAdjustmentGadget::enforce(
+                cs.clone(),
+                block.block_header.clone(),
+                difficulty,
+                BlockTargetGadget::mainnet_pow_limit(),
+            )?;
+        }
+
+        // Accumulate this block's work contribution.
+        let work = Self::block_work(cs.clone(), &target)?;
+        Ok(total_work + work)
+    }
+
+    // Computes a block's proof-of-work contribution, work = floor(2^256 / (target + 1)).
+    //
+    // The defining identity `work * (target + 1) + r == 2^256`, with `0 <= r < target + 1`,
+    // cannot be checked as a single field equation: both `2^256` and the product
+    // `work * (target + 1)` exceed the scalar modulus and would silently reduce, letting
+    // a prover forge `work`. Instead the identity is enforced over the integers with a
+    // 64-bit-limb schoolbook multiplication and explicit carry propagation, so every
+    // intermediate stays below the modulus.
+    fn block_work(
+        cs: ConstraintSystemRef<F>,
+        target: &FpVar<F>,
+    ) -> Result<FpVar<F>, SynthesisError> {
+        const LIMBS: usize = 4;
+        let radix = BigUint::from(1u8) << 64;
+        let two_pow_256 = BigUint::from(1u8) << 256;
+
+        let divisor = target + FpVar::one();
+        let work = FpVar::new_witness(cs.clone(), || {
+            let divisor: BigUint = divisor.value()?.into();
+            Ok(F::from(&two_pow_256 / &divisor))
+        })?;
+        let remainder = FpVar::new_witness(cs.clone(), || {
+            let divisor: BigUint = divisor.value()?.into();
+            Ok(F::from(&two_pow_256 % &divisor))
+        })?;
+
+        // 0 <= r < target + 1. Both operands are below (p - 1) / 2 (target stays under
+        // the pow limit, ~2^224), so the field comparison is sound here.
+        remainder.enforce_cmp(&divisor, Ordering::Less, false)?;
+
+        // Decompose the operands into 64-bit limbs, so each partial product q_i * d_j is
+        // below 2^128 and each column sum below the modulus.
+        let q = Self::into_limbs(cs.clone(), &work, LIMBS)?;
+        let d = Self::into_limbs(cs.clone(), &divisor, LIMBS)?;
+        let r = Self::into_limbs(cs.clone(), &remainder, LIMBS)?;
+
+        let radix = FpVar::new_constant(cs.clone(), F::from(radix))?;
+        let mut carry = FpVar::<F>::zero();
+        for k in 0..2 * LIMBS {
+            // Column k of the product, plus the remainder limb and the carry in.
+            let mut column = FpVar::<F>::zero();
+            for i in 0..LIMBS {
+                for j in 0..LIMBS {
+                    if i + j == k {
+                        column += &q[i] * &d[j];
+                    }
+                }
+            }
+            if k < LIMBS {
+                column += &r[k];
+            }
+            column += &carry;
+
+            // The only non-zero limb of 2^256 is a single 1 at limb index 4.
+            let expected = if k == 4 {
+                FpVar::<F>::one()
+            } else {
+                FpVar::<F>::zero()
+            };
+            let carry_out = FpVar::new_witness(cs.clone(), || {
+                let column: BigUint = column.value()?.into();
+                let expected: BigUint = if k == 4 { 1u8.into() } else { 0u8.into() };
+                Ok(F::from((column - expected) >> 64))
+            })?;
+            // Carries stay well under 2^72.
+            Self::enforce_bit_length(&carry_out, 72)?;
+            (&carry_out * &radix + &expected).enforce_equal(&column)?;
+            carry = carry_out;
+        }
+        // No overflow past 2^256.
+        carry.enforce_equal(&FpVar::zero())?;
+
+        Ok(work)
+    }
+
+    // Witnesses the little-endian 64-bit limbs of `value` and enforces both that each
+    // limb is 64 bits wide and that they recompose to `value`.
+    fn into_limbs(
+        cs: ConstraintSystemRef<F>,
+        value: &FpVar<F>,
+        n_limbs: usize,
+    ) -> Result<Vec<FpVar<F>>, SynthesisError> {
+        let mask = (BigUint::from(1u8) << 64) - 1u8;
+        let radix = FpVar::new_constant(cs.clone(), F::from(BigUint::from(1u8) << 64))?;
+
+        let mut limbs = Vec::with_capacity(n_limbs);
+        for i in 0..n_limbs {
+            let limb = FpVar::new_witness(cs.clone(), || {
+                let value: BigUint = value.value()?.into();
+                Ok(F::from((value >> (64 * i)) & &mask))
+            })?;
+            Self::enforce_bit_length(&limb, 64)?;
+            limbs.push(limb);
+        }
+
+        let mut acc = FpVar::<F>::zero();
+        let mut shift = FpVar::<F>::one();
+        for limb in &limbs {
+            acc += limb * &shift;
+            shift *= &radix;
+        }
+        acc.enforce_equal(value)?;
+
+        Ok(limbs)
+    }
+
+    // Enforces that `value` fits in `n` bits by pinning the higher bits to zero.
+    fn enforce_bit_length(value: &FpVar<F>, n: usize) -> Result<(), SynthesisError> {
+        for bit in value.to_bits_le()?.iter().skip(n) {
+            bit.enforce_equal(&Boolean::constant(false))?;
+        }
         Ok(())
     }
 }
@@ -48,6 +211,7 @@ impl<F: PrimeField> BTCBlockCheckerGadget<F> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::get_target;
     use crate::read_blocks;
     use crate::tests::get_test_block;
     use crate::utils::{Block, BlockHashVar, BlockVar};
@@ -81,7 +245,15 @@ mod tests {
                 let block_var =
                     BlockVar::new_variable(cs.clone(), || Ok(block), AllocationMode::Witness)
                         .unwrap();
-                let res = BTCBlockCheckerGadget::<Fr>::check_block(cs.clone(), block_var);
+                let total_work = FpVar::<Fr>::zero();
+                let res =
+                    BTCBlockCheckerGadget::<Fr>::check_block(
+                        cs.clone(),
+                        block_var,
+                        total_work,
+                        None,
+                        None,
+                    );
                 assert!(res.is_ok());
                 assert!(cs.is_satisfied().unwrap());
                 prev_block_hash = block_hash.clone();
@@ -100,7 +272,9 @@ mod tests {
         };
         let block_var =
             BlockVar::new_variable(cs.clone(), || Ok(block), AllocationMode::Witness).unwrap();
-        BTCBlockCheckerGadget::<Fr>::check_block(cs.clone(), block_var).unwrap();
+        let total_work = FpVar::<Fr>::zero();
+        BTCBlockCheckerGadget::<Fr>::check_block(cs.clone(), block_var, total_work, None, None)
+            .unwrap();
         assert!(cs.is_satisfied().unwrap());
     }
 
@@ -142,4 +316,48 @@ mod tests {
         let is_eq = prev_block_hash_var.is_eq(block_header_var).unwrap();
         assert!(is_eq.value().unwrap());
     }
+
+    // Asserts that the gadget's witnessed work matches floor(2^256 / (target + 1))
+    // and that the limb decomposition satisfies the constraint system.
+    fn assert_block_work_matches(target: BigUint) {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let target_var =
+            FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(target.clone()))).unwrap();
+
+        let work = BTCBlockCheckerGadget::block_work(cs.clone(), &target_var).unwrap();
+
+        let two_pow_256 = BigUint::from(1u8) << 256;
+        let expected = two_pow_256 / (target + BigUint::from(1u8));
+        assert_eq!(work.value().unwrap(), Fr::from(expected));
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn block_work_matches_reference_for_real_target() {
+        let target = get_target(&get_test_block().blockHeaders[0]);
+        assert_block_work_matches(target);
+    }
+
+    #[test]
+    fn block_work_matches_reference_near_pow_limit() {
+        let target: BigUint = BlockTargetGadget::<Fr>::mainnet_pow_limit().into();
+        assert_block_work_matches(target);
+    }
+
+    #[test]
+    fn block_work_rejects_forged_value() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let target = get_target(&get_test_block().blockHeaders[0]);
+        let target_var = FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(target))).unwrap();
+
+        let work = BTCBlockCheckerGadget::block_work(cs.clone(), &target_var).unwrap();
+
+        // The honest assignment fixes `work` to floor(2^256 / (target + 1)), so
+        // pinning it to any other constant must leave the system unsatisfied.
+        let forged = work.value().unwrap() + Fr::from(1u8);
+        let forged_var = FpVar::new_constant(cs.clone(), forged).unwrap();
+        work.enforce_equal(&forged_var).unwrap();
+
+        assert!(!cs.is_satisfied().unwrap());
+    }
 }
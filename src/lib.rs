@@ -51,11 +51,17 @@ pub fn get_block_hash(block_header: &Vec<u8>) -> String {
 
 /// Returns the target as a BigUint
 /// Target is computed from the "bits" field. The bits field is found in the 72..76 bytes of the block header
+/// For compact targets with an exponent below 3 the mantissa is right-shifted instead of scaled up,
+/// matching the full nBits decoding rule (a naive `exponent - 3` would underflow).
 pub fn get_target(block_header: &Vec<u8>) -> BigUint {
     let target_bytes = &block_header[72..76];
-    let exponent = target_bytes[3];
+    let exponent = target_bytes[3] as u32;
     let mantissa = BigUint::from_bytes_le(&target_bytes[0..3]);
-    mantissa * BigUint::from(256 as u16).pow(exponent as u32 - 3)
+    if exponent <= 3 {
+        mantissa >> (8 * (3 - exponent))
+    } else {
+        mantissa << (8 * (exponent - 3))
+    }
 }
 
 #[cfg(test)]
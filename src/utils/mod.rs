@@ -4,7 +4,9 @@ use ark_crypto_primitives::crh::sha256::constraints::DigestVar;
 use ark_ff::PrimeField;
 use ark_r1cs_std::{
     alloc::{AllocVar, AllocationMode},
+    fields::fp::FpVar,
     uint8::UInt8,
+    ToConstraintFieldGadget,
 };
 use ark_relations::r1cs::{Namespace, SynthesisError};
 use num_bigint::BigUint;
@@ -52,11 +54,48 @@ impl<F: PrimeField> AllocVar<Vec<u8>, F> for BlockHeaderVar<F> {
     ) -> Result<Self, SynthesisError> {
         let cs = cs.into();
         let block_header = f()?.borrow().clone();
+        assert_eq!(
+            block_header.len(),
+            80,
+            "a Bitcoin block header must be exactly 80 bytes"
+        );
         let block_header = UInt8::new_witness_vec(cs, &block_header)?;
         Ok(Self { block_header })
     }
 }
 
+impl<F: PrimeField> BlockHeaderVar<F> {
+    /// Block version, little-endian, bytes `0..4`.
+    pub fn version(&self) -> Result<FpVar<F>, SynthesisError> {
+        Ok(self.block_header[0..4].to_constraint_field()?[0].clone())
+    }
+
+    /// Hash of the previous block header, bytes `4..36`.
+    pub fn prev_blockhash(&self) -> &[UInt8<F>] {
+        &self.block_header[4..36]
+    }
+
+    /// Merkle root committing to the block's transactions, bytes `36..68`.
+    pub fn merkle_root(&self) -> &[UInt8<F>] {
+        &self.block_header[36..68]
+    }
+
+    /// Block timestamp, little-endian seconds since the Unix epoch, bytes `68..72`.
+    pub fn time(&self) -> Result<FpVar<F>, SynthesisError> {
+        Ok(self.block_header[68..72].to_constraint_field()?[0].clone())
+    }
+
+    /// Compact encoded target (`nBits`), bytes `72..76`.
+    pub fn bits(&self) -> &[UInt8<F>] {
+        &self.block_header[72..76]
+    }
+
+    /// Nonce, little-endian, bytes `76..80`.
+    pub fn nonce(&self) -> Result<FpVar<F>, SynthesisError> {
+        Ok(self.block_header[76..80].to_constraint_field()?[0].clone())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Block {
     pub block_header: Vec<u8>,